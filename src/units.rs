@@ -6,18 +6,43 @@
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Result;
 use dbus::blocking::Connection;
+use dbus::channel::MatchingReceiver;
+use dbus::message::MatchRule;
 use int_enum::IntEnum;
 use serde_repr::*;
 use struct_field_names_as_array::FieldNamesAsArray;
 use strum_macros::EnumIter;
 use strum_macros::EnumString;
+use sysinfo::Pid;
+use sysinfo::ProcessesToUpdate;
+use sysinfo::System;
 use tracing::debug;
 use tracing::error;
 
+/// One row of `org.freedesktop.systemd1.Manager.ListUnits()`: (name, description, load state,
+/// active state, sub state, followed unit, unit path, job id, job type, job path). Given a
+/// name once here rather than spelled out at each call site that passes a `ListUnits` row
+/// around.
+type RawUnit = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    dbus::Path<'static>,
+    u32,
+    String,
+    dbus::Path<'static>,
+);
+
 #[derive(
     serde::Serialize, serde::Deserialize, Clone, Debug, Default, Eq, FieldNamesAsArray, PartialEq,
 )]
@@ -26,12 +51,14 @@ use tracing::error;
 pub struct SystemdUnitStats {
     pub active_units: u64,
     pub automount_units: u64,
+    pub bad_setting_units: u64,
     pub device_units: u64,
     pub failed_units: u64,
     pub inactive_units: u64,
     pub jobs_queued: u64,
     pub loaded_units: u64,
     pub masked_units: u64,
+    pub merged_units: u64,
     pub mount_units: u64,
     pub not_found_units: u64,
     pub path_units: u64,
@@ -39,11 +66,16 @@ pub struct SystemdUnitStats {
     pub service_units: u64,
     pub slice_units: u64,
     pub socket_units: u64,
+    pub stub_units: u64,
     pub target_units: u64,
     pub timer_units: u64,
     pub total_units: u64,
     pub service_stats: HashMap<String, ServiceStats>,
     pub unit_states: HashMap<String, UnitStates>,
+    // Keyed by "<unit-type>.<substate>" (e.g. "socket.listening"), since SubState values
+    // are unit-type-specific and not a fixed enum
+    pub sub_state_stats: HashMap<String, u64>,
+    pub dependency_stats: DependencyStats,
 }
 
 /// Selected subset of metrics collected from systemd OrgFreedesktopSystemd1Service
@@ -67,6 +99,64 @@ pub struct ServiceStats {
     pub tasks_current: u64,
     pub timeout_clean_usec: u64,
     pub watchdog_usec: u64,
+    /// Sum of resident memory across the service's main PIDs, as seen in the single
+    /// sysinfo snapshot taken for this scrape
+    pub process_resident_memory_bytes: u64,
+    /// Sum of CPU usage across the service's main PIDs, in thousandths of a percent
+    /// (e.g. 1500 means 1.5%) so the field stays an integer like the rest of this struct
+    pub process_cpu_percent_milli: u64,
+    /// The service's PIDs ranked by CPU usage, truncated to `TOP_PROCESS_COUNT`
+    pub top_processes: Vec<TopProcess>,
+}
+
+/// A single process backing a service, sampled from sysinfo alongside the dbus-reported
+/// command line for that PID
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Debug, Default, Eq, FieldNamesAsArray, PartialEq,
+)]
+pub struct TopProcess {
+    pub pid: u32,
+    pub command: String,
+    pub cpu_percent_milli: u64,
+    pub resident_memory_bytes: u64,
+}
+
+/// Aggregate metrics over the dependency graph built from each selected unit's
+/// Requires/Requisite/Wants/BindsTo/Conflicts/After/Before/WantedBy/RequiredBy properties.
+/// Only populated when `config.units.dependency_stats` is enabled, since it costs one extra
+/// dbus round-trip per selected unit.
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Debug, Default, Eq, FieldNamesAsArray, PartialEq,
+)]
+pub struct DependencyStats {
+    /// Total Requires/Requisite/Wants/BindsTo/Conflicts edges seen across selected units
+    pub total_edges: u64,
+    /// Edges naming a unit that `list_units` didn't return - recorded rather than panicking,
+    /// since a dependency can legitimately name a unit that doesn't currently exist
+    pub unresolved_edges: u64,
+    pub unit_degree: HashMap<String, UnitDependencyDegree>,
+    /// Active units with StopWhenUnneeded=true and no active unit in their WantedBy/RequiredBy,
+    /// analogous to systemd's own stop-when-unneeded logic
+    pub unneeded_units: Vec<String>,
+    /// Each entry is one cycle found while walking the After/Before ordering graph
+    pub ordering_cycles: Vec<Vec<String>>,
+}
+
+/// In/out edge counts for a single unit in the dependency graph
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    FieldNamesAsArray,
+    PartialEq,
+)]
+pub struct UnitDependencyDegree {
+    pub in_degree: u64,
+    pub out_degree: u64,
 }
 
 /// Collection of a Unit active and load state: https://www.freedesktop.org/software/systemd/man/org.freedesktop.systemd1.html
@@ -76,6 +166,9 @@ pub struct ServiceStats {
 pub struct UnitStates {
     pub active_state: SystemdUnitActiveState,
     pub load_state: SystemdUnitLoadState,
+    // Only populated when config.units.substate_stats is enabled, since SubState is
+    // unit-type-specific and has no fixed enum to default to
+    pub sub_state: String,
     // Unhealthy is only calculated for SystemdUnitLoadState::loaded units based on !SystemdActiveState::active
     // and !SystemdUnitLoadState::masked
     pub unhealthy: bool,
@@ -137,20 +230,148 @@ pub enum SystemdUnitLoadState {
     error = 2,
     masked = 3,
     not_found = 4,
+    stub = 5,
+    merged = 6,
+    bad_setting = 7,
 }
 
 pub const SERVICE_FIELD_NAMES: &[&str] = &ServiceStats::FIELD_NAMES_AS_ARRAY;
 pub const UNIT_FIELD_NAMES: &[&str] = &SystemdUnitStats::FIELD_NAMES_AS_ARRAY;
 pub const UNIT_STATES_FIELD_NAMES: &[&str] = &UnitStates::FIELD_NAMES_AS_ARRAY;
+pub const DEPENDENCY_FIELD_NAMES: &[&str] = &DependencyStats::FIELD_NAMES_AS_ARRAY;
 
-/// Pull out selected systemd service statistics
-fn parse_service(c: &Connection, name: &str, path: &str) -> Result<ServiceStats, dbus::Error> {
+/// How `config.units.state_stats_allowlist`/`state_stats_blocklist` entries should be
+/// interpreted when deciding whether a unit is selected for state stats collection
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitMatchMode {
+    /// Exact, case-sensitive unit name comparison (the historical behaviour)
+    #[default]
+    Literal,
+    /// Shell-style globs, e.g. `*.slice` or `user@*.service`
+    Glob,
+    /// A set of regular expressions
+    Regex,
+}
+
+/// Allow/blocklist entries precompiled once per `parse_unit_state` pass so per-unit
+/// selection in `parse_state` is a cheap `is_match` instead of recompiling patterns
+/// (or doing linear string comparisons) for every one of potentially thousands of units
+pub enum UnitMatcher {
+    Literal(Vec<String>),
+    Glob(Vec<glob::Pattern>),
+    Regex(regex::RegexSet),
+    /// Every pattern the operator configured failed to compile. Kept distinct from an
+    /// empty (unconfigured) matcher - an empty allowlist means "match everything", but a
+    /// configured-and-broken allowlist must fail closed and match nothing, or a typo'd
+    /// pattern would silently turn into "collect every unit" instead of "collect none".
+    Invalid,
+}
+
+impl UnitMatcher {
+    pub fn compile(patterns: &[String], mode: UnitMatchMode) -> Self {
+        match mode {
+            UnitMatchMode::Literal => UnitMatcher::Literal(patterns.to_vec()),
+            UnitMatchMode::Glob => {
+                let compiled: Vec<glob::Pattern> = patterns
+                    .iter()
+                    .filter_map(|pattern| match glob::Pattern::new(pattern) {
+                        Ok(compiled) => Some(compiled),
+                        Err(err) => {
+                            error!("Ignoring invalid glob pattern '{}': {:?}", pattern, err);
+                            None
+                        }
+                    })
+                    .collect();
+                if compiled.is_empty() && !patterns.is_empty() {
+                    error!(
+                        "Every configured glob pattern {:?} was invalid, matching nothing",
+                        patterns
+                    );
+                    UnitMatcher::Invalid
+                } else {
+                    UnitMatcher::Glob(compiled)
+                }
+            }
+            UnitMatchMode::Regex => {
+                // Compile patterns individually first, same fail-closed policy as the
+                // Glob arm above: drop only the patterns that don't compile rather than
+                // letting one bad regex take down an otherwise-valid set
+                let valid: Vec<String> = patterns
+                    .iter()
+                    .filter(|pattern| match regex::Regex::new(pattern) {
+                        Ok(_) => true,
+                        Err(err) => {
+                            error!("Ignoring invalid regex pattern '{}': {:?}", pattern, err);
+                            false
+                        }
+                    })
+                    .cloned()
+                    .collect();
+                if valid.is_empty() && !patterns.is_empty() {
+                    error!(
+                        "Every configured regex pattern {:?} was invalid, matching nothing",
+                        patterns
+                    );
+                    UnitMatcher::Invalid
+                } else {
+                    match regex::RegexSet::new(&valid) {
+                        Ok(set) => UnitMatcher::Regex(set),
+                        Err(err) => {
+                            error!(
+                                "Ignoring invalid regex set {:?}, matching nothing: {:?}",
+                                valid, err
+                            );
+                            UnitMatcher::Invalid
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            UnitMatcher::Literal(patterns) => patterns.is_empty(),
+            UnitMatcher::Glob(patterns) => patterns.is_empty(),
+            UnitMatcher::Regex(set) => set.is_empty(),
+            // Not "empty" in the unconfigured sense - treating this as empty would fall
+            // back to "match everything" semantics, exactly the inversion this variant
+            // exists to avoid
+            UnitMatcher::Invalid => false,
+        }
+    }
+
+    pub fn is_match(&self, unit_name: &str) -> bool {
+        match self {
+            UnitMatcher::Literal(patterns) => patterns.iter().any(|p| p == unit_name),
+            UnitMatcher::Glob(patterns) => patterns.iter().any(|p| p.matches(unit_name)),
+            UnitMatcher::Regex(set) => set.is_match(unit_name),
+            UnitMatcher::Invalid => false,
+        }
+    }
+}
+
+/// How many of a service's processes to keep in [`ServiceStats::top_processes`], ranked by CPU
+const TOP_PROCESS_COUNT: usize = 5;
+
+/// Pull out selected systemd service statistics, enriched with live per-process resource
+/// data looked up from `sys`. `sys` is expected to already have been refreshed by the caller
+/// once per [`parse_unit_state`] pass rather than once per service, since refreshing sysinfo's
+/// process list is the expensive part.
+fn parse_service(
+    c: &Connection,
+    name: &str,
+    path: &str,
+    sys: &System,
+) -> Result<ServiceStats, dbus::Error> {
     debug!("Parsing service {} stats", name);
     let p = c.with_proxy("org.freedesktop.systemd1", path, Duration::new(2, 0));
     use crate::dbus::units::OrgFreedesktopSystemd1Service;
     use crate::dbus::units::OrgFreedesktopSystemd1Unit;
 
-    let processes = match p.get_processes()?.len().try_into() {
+    let main_processes = p.get_processes()?;
+    let processes = match main_processes.len().try_into() {
         Ok(procs) => procs,
         Err(err) => {
             error!(
@@ -161,6 +382,33 @@ fn parse_service(c: &Connection, name: &str, path: &str) -> Result<ServiceStats,
         }
     };
 
+    let mut process_resident_memory_bytes = 0u64;
+    let mut process_cpu_percent_milli = 0u64;
+    let mut samples = Vec::with_capacity(main_processes.len());
+    for (_cgroup_path, pid, command) in &main_processes {
+        let Some(process) = sys.process(Pid::from_u32(*pid)) else {
+            // The PID can legitimately exit between the dbus GetProcesses() call and this
+            // sysinfo lookup; skip it rather than treating it as an error
+            debug!("Skipping vanished pid {} for service {}", pid, name);
+            continue;
+        };
+        // `Process::memory()` returns bytes directly on sysinfo 0.30+, which is what
+        // `refresh_processes(ProcessesToUpdate::All)` in `collect_unit_stats` requires; no KiB conversion
+        // needed here. There's no Cargo.toml in this tree to pin the version against, so
+        // if sysinfo is ever vendored below 0.30 this'll need a `* 1024` put back.
+        let resident_memory_bytes = process.memory();
+        let cpu_percent_milli = (process.cpu_usage() * 1000.0) as u64;
+        process_resident_memory_bytes += resident_memory_bytes;
+        process_cpu_percent_milli += cpu_percent_milli;
+        samples.push(TopProcess {
+            pid: *pid,
+            command: command.clone(),
+            cpu_percent_milli,
+            resident_memory_bytes,
+        });
+    }
+    let top_processes = top_processes_by_cpu(samples, TOP_PROCESS_COUNT);
+
     Ok(ServiceStats {
         active_enter_timestamp: p.active_enter_timestamp()?,
         active_exit_timestamp: p.active_exit_timestamp()?,
@@ -178,9 +426,24 @@ fn parse_service(c: &Connection, name: &str, path: &str) -> Result<ServiceStats,
         tasks_current: p.tasks_current()?,
         timeout_clean_usec: p.timeout_clean_usec()?,
         watchdog_usec: p.watchdog_usec()?,
+        process_resident_memory_bytes,
+        process_cpu_percent_milli,
+        top_processes,
     })
 }
 
+/// Sort process samples by CPU usage (ties broken by resident memory) and keep only the
+/// top `limit`, so `top_processes` stays bounded regardless of how many PIDs a service has
+fn top_processes_by_cpu(mut samples: Vec<TopProcess>, limit: usize) -> Vec<TopProcess> {
+    samples.sort_by(|a, b| {
+        b.cpu_percent_milli
+            .cmp(&a.cpu_percent_milli)
+            .then(b.resident_memory_bytes.cmp(&a.resident_memory_bytes))
+    });
+    samples.truncate(limit);
+    samples
+}
+
 /// Check if we're a loaded unit and if so evaluate if we're acitive or not
 /// If we're not
 /// Only potentially mark unhealthy for LOADED units that are not active
@@ -194,6 +457,12 @@ pub fn is_unit_unhealthy(
         // An admin can change a unit to be masked on purpose
         // so we are going to ignore all masked units due to that
         SystemdUnitLoadState::masked => false,
+        // stub/merged units are transient placeholders (e.g. a unit only referenced via
+        // Also= or as a merge target) and not something an operator acted on, so don't
+        // flag them as unhealthy
+        SystemdUnitLoadState::stub | SystemdUnitLoadState::merged => false,
+        // A bad setting in the unit file is a real, actionable problem
+        SystemdUnitLoadState::bad_setting => true,
         // Otherwise, we're unhealthy
         _ => true,
     }
@@ -207,22 +476,23 @@ pub fn parse_state(
         String,
         String, // load state
         String, // active state
-        String,
+        String, // sub state
         String,
         dbus::Path<'static>,
         u32,
         String,
         dbus::Path<'static>,
     ),
-    allowlist: &[String],
-    blocklist: &[String],
+    allowlist: &UnitMatcher,
+    blocklist: &UnitMatcher,
+    collect_substate: bool,
 ) {
     let unit_name = unit.0;
-    if blocklist.contains(&unit_name) {
+    if blocklist.is_match(&unit_name) {
         debug!("Skipping state stats for {} due to blocklist", unit_name);
         return;
     }
-    if !allowlist.is_empty() && !allowlist.contains(&unit_name) {
+    if !allowlist.is_empty() && !allowlist.is_match(&unit_name) {
         debug!(
             "Skipping state stats for {} due to not being in allowlist",
             unit_name
@@ -231,14 +501,35 @@ pub fn parse_state(
     }
     let active_state =
         SystemdUnitActiveState::from_str(&unit.3).unwrap_or(SystemdUnitActiveState::unknown);
-    let load_state = SystemdUnitLoadState::from_str(&unit.2.replace('-', "_"))
-        .unwrap_or(SystemdUnitLoadState::unknown);
+    let load_state =
+        SystemdUnitLoadState::from_str(&unit.2.replace('-', "_")).unwrap_or_else(|_| {
+            // The systemd docs explicitly call out that the LOAD value list is not constant
+            // across releases, so log unmapped strings instead of silently swallowing them
+            debug!(
+                "Unmapped systemd load state '{}' seen, treating as unknown",
+                unit.2
+            );
+            SystemdUnitLoadState::unknown
+        });
+
+    let sub_state = if collect_substate {
+        if let Some(unit_type) = unit_name.split('.').nth(1) {
+            *stats
+                .sub_state_stats
+                .entry(format!("{}.{}", unit_type, unit.4))
+                .or_insert(0) += 1;
+        }
+        unit.4
+    } else {
+        String::new()
+    };
 
     stats.unit_states.insert(
         unit_name.clone(),
         UnitStates {
             active_state,
             load_state,
+            sub_state,
             unhealthy: is_unit_unhealthy(active_state, load_state),
         },
     );
@@ -279,6 +570,9 @@ fn parse_unit(
         "loaded" => stats.loaded_units += 1,
         "masked" => stats.masked_units += 1,
         "not-found" => stats.not_found_units += 1,
+        "stub" => stats.stub_units += 1,
+        "merged" => stats.merged_units += 1,
+        "bad-setting" => stats.bad_setting_units += 1,
         _ => debug!("{} is not loaded. It's {}", unit.0, unit.2),
     };
     // Count unit status
@@ -288,29 +582,54 @@ fn parse_unit(
         "inactive" => stats.inactive_units += 1,
         unknown => debug!("Found unhandled '{}' unit state", unknown),
     };
-    // Count jobs queued
-    if unit.7 != 0 {
-        stats.jobs_queued += 1;
-    }
+    // jobs_queued is set separately in parse_unit_state from the Manager's own ListJobs()
+    // count, the same source the event-driven path's refresh_jobs_queued() uses, rather
+    // than derived per-unit here - keeping one definition on both collection paths
 }
 
 /// Pull all units from dbus and count how system is setup and behaving
 pub fn parse_unit_state(
     config: &crate::config::Config,
 ) -> Result<SystemdUnitStats, Box<dyn std::error::Error + Send + Sync>> {
+    collect_unit_stats(config, true).map(|(stats, _units)| stats)
+}
+
+/// Shared implementation behind [`parse_unit_state`] and the event-driven path's topology
+/// reseeds (see [`sweep_topology_dirty`]). `sample_process_resources` gates the sysinfo
+/// double-refresh and per-service `parse_service` calls: the polling path always wants them,
+/// but a reseed triggered by a single UnitNew/UnitRemoved signal can't afford to block the
+/// dbus dispatch loop on a `MINIMUM_CPU_UPDATE_INTERVAL` sleep plus per-process sampling, so
+/// it passes `false` and leaves `service_stats` for the caller to carry over. Also returns the
+/// raw `list_units()` rows so callers that need per-unit state (the event-driven shadow map)
+/// don't have to pay for a second ListUnits() round trip.
+fn collect_unit_stats(
+    config: &crate::config::Config,
+    sample_process_resources: bool,
+) -> Result<(SystemdUnitStats, Vec<RawUnit>), Box<dyn std::error::Error + Send + Sync>> {
     if !config.units.state_stats_allowlist.is_empty() {
         debug!(
             "Using unit state allowlist: {:?}",
             config.units.state_stats_allowlist
         );
     }
-    if !config.units.state_stats_allowlist.is_empty() {
+    if !config.units.state_stats_blocklist.is_empty() {
         debug!(
             "Using unit state blocklist: {:?}",
-            config.units.state_stats_allowlist
+            config.units.state_stats_blocklist
         );
     }
 
+    // Precompile the allow/blocklist once per scrape rather than per unit, since glob
+    // and regex compilation isn't free and a scrape can walk thousands of units
+    let allow_matcher = UnitMatcher::compile(
+        &config.units.state_stats_allowlist,
+        config.units.state_stats_match_mode,
+    );
+    let block_matcher = UnitMatcher::compile(
+        &config.units.state_stats_blocklist,
+        config.units.state_stats_match_mode,
+    );
+
     std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &config.monitord.dbus_address);
     let mut stats = SystemdUnitStats::default();
     let c = Connection::new_system()?;
@@ -322,25 +641,45 @@ pub fn parse_unit_state(
     use crate::dbus::systemd::OrgFreedesktopSystemd1Manager;
     let units = p.list_units()?;
     stats.total_units = units.len() as u64;
-    for unit in units {
+    // The Manager's own outstanding job count is the single source of truth for
+    // jobs_queued, shared with the event-driven path's refresh_jobs_queued() - not a
+    // per-unit derived count, so the two collection modes can never disagree
+    stats.jobs_queued = p.list_jobs()?.len() as u64;
+
+    // Refreshed once per pass rather than once per service, since enumerating processes is
+    // the expensive part of a sysinfo refresh and `config.services` is typically a small,
+    // fixed set of units we care about
+    let mut sys = System::new();
+    if sample_process_resources && !config.services.is_empty() {
+        // sysinfo computes cpu_usage() as a delta between two refreshes - a single
+        // refresh leaves every process reporting 0.0, so we need an initial sample, a
+        // minimum interval, then a second sample before CPU numbers mean anything
+        sys.refresh_processes(ProcessesToUpdate::All);
+        std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+        sys.refresh_processes(ProcessesToUpdate::All);
+    }
+
+    for unit in &units {
         // Collect unit types + states counts
         parse_unit(&mut stats, unit.clone());
 
-        // Collect per unit state stats - ActiveState + LoadState
-        // Not collecting SubState (yet)
-        if config.units.state_stats {
+        // Collect per unit state stats - ActiveState + LoadState (+ SubState if enabled)
+        if config.units.state_stats || config.units.substate_stats {
             parse_state(
                 &mut stats,
                 unit.clone(),
-                &config.units.state_stats_allowlist,
-                &config.units.state_stats_blocklist,
+                &allow_matcher,
+                &block_matcher,
+                config.units.substate_stats,
             );
         }
 
-        // Collect service stats
-        if config.services.contains(&unit.0) {
+        // Collect service stats - skipped when `sample_process_resources` is false, since
+        // that's exactly the sysinfo sampling above that this call was told to avoid; the
+        // caller is responsible for carrying over whatever service_stats it already has
+        if sample_process_resources && config.services.contains(&unit.0) {
             debug!("Collecting service stats for {:?}", &unit);
-            match parse_service(&c, &unit.0, &unit.6) {
+            match parse_service(&c, &unit.0, &unit.6, &sys) {
                 Ok(service_stats) => {
                     stats.service_stats.insert(unit.0.clone(), service_stats);
                 }
@@ -351,8 +690,714 @@ pub fn parse_unit_state(
             }
         }
     }
+
+    // Like the service stats above, skipped on a `sample_process_resources = false` reseed:
+    // this walks Requires/Wants/etc. over dbus for every allowed unit, which is exactly the
+    // kind of per-unit round-trip burden that reseed is meant to avoid piling onto the dbus
+    // dispatch loop. The caller carries over the previous value instead of seeing it go blank.
+    if sample_process_resources && config.units.dependency_stats {
+        stats.dependency_stats = parse_dependencies(&c, &units, &allow_matcher, &block_matcher);
+    }
+
     debug!("unit stats: {:?}", stats);
-    Ok(stats)
+    Ok((stats, units))
+}
+
+/// Build the dependency graph for the selected units and compute [`DependencyStats`] from it.
+/// Tolerates dangling references (a dependency naming a unit `list_units` didn't return) by
+/// counting them in `unresolved_edges` rather than panicking.
+fn parse_dependencies(
+    c: &Connection,
+    units: &[RawUnit],
+    allowlist: &UnitMatcher,
+    blocklist: &UnitMatcher,
+) -> DependencyStats {
+    use crate::dbus::units::OrgFreedesktopSystemd1Unit;
+
+    let known_units: std::collections::HashSet<&str> = units.iter().map(|u| u.0.as_str()).collect();
+    let active_units: std::collections::HashSet<&str> = units
+        .iter()
+        .filter(|u| u.3 == "active")
+        .map(|u| u.0.as_str())
+        .collect();
+
+    let mut stats = DependencyStats::default();
+    // Ordering graph from After/Before, kept separate from the Requires/Wants edges below
+    // since it's only used for cycle detection, not for degree/edge counts
+    let mut ordering_edges: HashMap<String, Vec<String>> = HashMap::new();
+
+    for unit in units {
+        let unit_name = &unit.0;
+        if blocklist.is_match(unit_name) {
+            continue;
+        }
+        if !allowlist.is_empty() && !allowlist.is_match(unit_name) {
+            continue;
+        }
+
+        let p = c.with_proxy(
+            "org.freedesktop.systemd1",
+            unit.6.clone(),
+            Duration::new(2, 0),
+        );
+
+        let mut forward = p.requires().unwrap_or_default();
+        forward.extend(p.requisite().unwrap_or_default());
+        forward.extend(p.wants().unwrap_or_default());
+        forward.extend(p.binds_to().unwrap_or_default());
+        forward.extend(p.conflicts().unwrap_or_default());
+
+        stats.total_edges += forward.len() as u64;
+        stats
+            .unit_degree
+            .entry(unit_name.clone())
+            .or_default()
+            .out_degree += forward.len() as u64;
+        for dep in &forward {
+            if known_units.contains(dep.as_str()) {
+                stats.unit_degree.entry(dep.clone()).or_default().in_degree += 1;
+            } else {
+                stats.unresolved_edges += 1;
+            }
+        }
+
+        for dep in p.after().unwrap_or_default() {
+            // "unit After dep" means dep must start first: ordering edge dep -> unit
+            ordering_edges
+                .entry(dep)
+                .or_default()
+                .push(unit_name.clone());
+        }
+        for dep in p.before().unwrap_or_default() {
+            // "unit Before dep" means unit must start first: ordering edge unit -> dep
+            ordering_edges
+                .entry(unit_name.clone())
+                .or_default()
+                .push(dep);
+        }
+
+        if p.stop_when_unneeded().unwrap_or(false) && active_units.contains(unit_name.as_str()) {
+            let reverse_deps = p
+                .wanted_by()
+                .unwrap_or_default()
+                .into_iter()
+                .chain(p.required_by().unwrap_or_default());
+            let has_active_dependent = reverse_deps
+                .into_iter()
+                .any(|dep| active_units.contains(dep.as_str()));
+            if !has_active_dependent {
+                stats.unneeded_units.push(unit_name.clone());
+            }
+        }
+    }
+
+    stats.ordering_cycles = find_ordering_cycles(&ordering_edges);
+    stats
+}
+
+/// Three-color (white/gray/black) DFS over the After/Before ordering graph: a back-edge to a
+/// gray node (one still on the current DFS stack) is an ordering cycle.
+fn find_ordering_cycles(ordering_edges: &HashMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Color {
+        White,
+        Gray,
+        Black,
+    }
+
+    fn visit(
+        node: &str,
+        ordering_edges: &HashMap<String, Vec<String>>,
+        colors: &mut HashMap<String, Color>,
+        stack: &mut Vec<String>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        colors.insert(node.to_string(), Color::Gray);
+        stack.push(node.to_string());
+
+        if let Some(neighbours) = ordering_edges.get(node) {
+            for neighbour in neighbours {
+                match colors
+                    .get(neighbour.as_str())
+                    .copied()
+                    .unwrap_or(Color::White)
+                {
+                    Color::White => visit(neighbour, ordering_edges, colors, stack, cycles),
+                    Color::Gray => {
+                        // Back-edge to a node still on the stack: report the cycle from
+                        // where it occurs on the stack through to here
+                        if let Some(start) = stack.iter().position(|n| n == neighbour) {
+                            let mut cycle: Vec<String> = stack[start..].to_vec();
+                            cycle.push(neighbour.clone());
+                            cycles.push(cycle);
+                        }
+                    }
+                    Color::Black => {}
+                }
+            }
+        }
+
+        stack.pop();
+        colors.insert(node.to_string(), Color::Black);
+    }
+
+    let mut colors: HashMap<String, Color> = HashMap::new();
+    let mut cycles = Vec::new();
+    let mut stack = Vec::new();
+    for node in ordering_edges.keys() {
+        if colors.get(node.as_str()).copied().unwrap_or(Color::White) == Color::White {
+            visit(node, ordering_edges, &mut colors, &mut stack, &mut cycles);
+        }
+    }
+    cycles
+}
+
+/// Window used to debounce `PropertiesChanged` bursts for the same unit: the first signal
+/// in a burst is applied immediately, further signals within this window are coalesced
+/// rather than re-applied, and the unit's state as of the *last* signal is re-read and
+/// applied once the burst has been quiet for this long. This is trailing-edge, not
+/// drop-on-conflict, so a unit flapping through several transitions in quick succession
+/// (e.g. activating -> active -> reloading on a fast restart) never leaves the aggregate
+/// counters pinned to a stale intermediate state.
+const PROPERTIES_CHANGED_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// A unit whose `PropertiesChanged` signal arrived mid-burst and hasn't been re-applied
+/// yet. [`sweep_pending_properties_changed`] applies it once `last_seen` is old enough.
+struct PendingPropertiesChanged {
+    path: dbus::Path<'static>,
+    last_seen: Instant,
+}
+
+/// Push-based alternative to [`parse_unit_state`]'s full `ListUnits` polling. Subscribes to
+/// the systemd Manager's `UnitNew`/`UnitRemoved`/`JobNew`/`JobRemoved` signals plus each
+/// unit's `PropertiesChanged` signal, and mutates `shared` incrementally as they arrive
+/// instead of rebuilding `SystemdUnitStats` from scratch every interval. This makes a scrape
+/// O(1) per transition rather than O(units), and catches transitions that happen between
+/// polls.
+///
+/// `shared` is the same `Arc<Mutex<SystemdUnitStats>>` the serialization path reads from, so
+/// consumers see one consistent struct regardless of which collection mode produced it.
+/// Intended to run for the lifetime of the process (e.g. on its own thread) when
+/// `config.units.event_driven` is enabled. It never returns: every failed pass, including a
+/// failure to connect or subscribe, is logged and retried with backoff, reconnecting and
+/// reseeding each time.
+pub fn watch_unit_state(
+    config: Arc<crate::config::Config>,
+    shared: Arc<Mutex<SystemdUnitStats>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &config.monitord.dbus_address);
+    let mut backoff = RECONNECT_BACKOFF_MIN;
+    loop {
+        let attempt_started = Instant::now();
+        if let Err(err) = run_event_driven_pass(&config, &shared) {
+            error!(
+                "systemd event watch disconnected, reseeding and reconnecting in {:?}: {:?}",
+                backoff, err
+            );
+            // A pass that stayed up for a while was genuinely subscribed and processing
+            // signals rather than failing to connect at all, so don't let one blip after
+            // a long healthy run ratchet the backoff up for good
+            if attempt_started.elapsed() >= RECONNECT_BACKOFF_MAX {
+                backoff = RECONNECT_BACKOFF_MIN;
+            }
+            std::thread::sleep(backoff);
+            backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+        }
+    }
+}
+
+/// Bounds for the reconnect backoff in [`watch_unit_state`]: without this, a persistent
+/// dbus outage (e.g. the system bus itself down) would spin reseeding in a tight CPU loop.
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+fn run_event_driven_pass(
+    config: &Arc<crate::config::Config>,
+    shared: &Arc<Mutex<SystemdUnitStats>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use crate::dbus::systemd::OrgFreedesktopSystemd1Manager;
+
+    let c = Connection::new_system()?;
+    let manager = c.with_proxy(
+        "org.freedesktop.systemd1",
+        "/org/freedesktop/systemd1",
+        Duration::new(5, 0),
+    );
+    manager.subscribe()?;
+
+    // Reseed from a full pass both on first start and after every reconnect, since we can't
+    // tell how many transitions were missed while disconnected - this is what guarantees the
+    // counters stay internally consistent rather than drifting further apart over time
+    let (stats, units) = collect_unit_stats(config, true)?;
+    *shared.lock().unwrap() = stats;
+    // `shadow` mirrors every unit's last-known state, independent of whether state_stats or
+    // substate_stats is enabled - apply_unit_state needs a reliable "old state" to decrement
+    // on every PropertiesChanged, and stats.unit_states alone isn't it: it's only populated
+    // when one of those flags is set, which would otherwise double-count every transition
+    // past the first when both are off.
+    let shadow: Arc<Mutex<HashMap<String, UnitStates>>> =
+        Arc::new(Mutex::new(build_unit_state_shadow(&units)));
+    debug!("systemd event watch (re)seeded, subscribing to unit/job signals");
+
+    let pending: Arc<Mutex<HashMap<String, PendingPropertiesChanged>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+
+    // UnitNew/UnitRemoved change the set of known units, which affects every aggregate
+    // counter (type counts, load/active counts, etc; `dependency_stats` is carried over
+    // rather than recomputed, see `sweep_topology_dirty`). Rather than reimplement parse_unit's/parse_state's bookkeeping in reverse for
+    // removal, just reseed - unit topology changes are rare next to state flaps, so the
+    // O(units) cost here is acceptable where it wouldn't be per PropertiesChanged. The
+    // actual reseed is deferred to `sweep_topology_dirty`: a boot or daemon-reload can fire
+    // UnitNew/UnitRemoved for hundreds of units in a burst, and reseeding synchronously
+    // inside the signal callback - which also samples sysinfo when services are configured -
+    // would block `c.process()` from dispatching anything else for the length of that burst.
+    let topology_dirty: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+    {
+        let topology_dirty = topology_dirty.clone();
+        let rule = MatchRule::new_signal("org.freedesktop.systemd1.Manager", "UnitNew");
+        c.add_match_no_cb(&rule.match_str())?;
+        c.start_receive(
+            rule,
+            Box::new(move |msg, _| {
+                debug!("UnitNew: {:?}", msg);
+                *topology_dirty.lock().unwrap() = Some(Instant::now());
+                true
+            }),
+        );
+    }
+    {
+        let pending = pending.clone();
+        let topology_dirty = topology_dirty.clone();
+        let rule = MatchRule::new_signal("org.freedesktop.systemd1.Manager", "UnitRemoved");
+        c.add_match_no_cb(&rule.match_str())?;
+        c.start_receive(
+            rule,
+            Box::new(move |msg, _| {
+                debug!("UnitRemoved: {:?}", msg);
+                // The removed unit can no longer fire PropertiesChanged, so drop any
+                // pending debounce entry for it rather than letting it sit forever
+                if let Ok((unit_name, _unit_path)) = msg.read2::<String, dbus::Path>() {
+                    pending.lock().unwrap().remove(&unit_name);
+                }
+                *topology_dirty.lock().unwrap() = Some(Instant::now());
+                true
+            }),
+        );
+    }
+
+    // Jobs queue/dequeue far more often than units come and go. Re-read the authoritative
+    // count from the Manager on each signal rather than maintaining an independent +1/-1
+    // counter, so this never drifts from (or is silently reset relative to) the polling
+    // path's definition of jobs_queued, which is recomputed from ListJobs each pass.
+    {
+        let shared = shared.clone();
+        let rule = MatchRule::new_signal("org.freedesktop.systemd1.Manager", "JobNew");
+        c.add_match_no_cb(&rule.match_str())?;
+        c.start_receive(
+            rule,
+            Box::new(move |msg, conn| {
+                debug!("JobNew: {:?}", msg);
+                refresh_jobs_queued(conn, &shared);
+                true
+            }),
+        );
+    }
+    {
+        let shared = shared.clone();
+        let rule = MatchRule::new_signal("org.freedesktop.systemd1.Manager", "JobRemoved");
+        c.add_match_no_cb(&rule.match_str())?;
+        c.start_receive(
+            rule,
+            Box::new(move |msg, conn| {
+                debug!("JobRemoved: {:?}", msg);
+                refresh_jobs_queued(conn, &shared);
+                true
+            }),
+        );
+    }
+
+    // ActiveState/LoadState/SubState transitions arrive as PropertiesChanged. Scoped to
+    // systemd's own bus name and unit object-path namespace, rather than matching every
+    // object on the system bus (logind, NetworkManager, ...) and relying on the proxy.id()
+    // error path below to filter out everything that isn't a unit.
+    let allow_matcher = Arc::new(UnitMatcher::compile(
+        &config.units.state_stats_allowlist,
+        config.units.state_stats_match_mode,
+    ));
+    let block_matcher = Arc::new(UnitMatcher::compile(
+        &config.units.state_stats_blocklist,
+        config.units.state_stats_match_mode,
+    ));
+    let collect_substate = config.units.substate_stats;
+    // Whether a unit's state belongs in the public stats.unit_states map, same gating
+    // parse_unit_state uses for parse_state - `shadow` above is always kept up to date
+    // regardless, since it exists purely for apply_unit_state's own bookkeeping
+    let record_public = config.units.state_stats || config.units.substate_stats;
+    {
+        let shared = shared.clone();
+        let shadow = shadow.clone();
+        let pending = pending.clone();
+        let allow_matcher = allow_matcher.clone();
+        let block_matcher = block_matcher.clone();
+        let mut rule =
+            MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged");
+        rule.sender = Some(dbus::BusName::new("org.freedesktop.systemd1").unwrap());
+        rule.path_is_namespace = true;
+        rule.path = Some(dbus::Path::new("/org/freedesktop/systemd1/unit").unwrap());
+        c.add_match_no_cb(&rule.match_str())?;
+        c.start_receive(
+            rule,
+            Box::new(move |msg, conn| {
+                apply_properties_changed(
+                    conn,
+                    &shared,
+                    &shadow,
+                    &pending,
+                    &allow_matcher,
+                    &block_matcher,
+                    collect_substate,
+                    record_public,
+                    &msg,
+                );
+                true
+            }),
+        );
+    }
+
+    loop {
+        // Tick at least once per debounce window so a burst's trailing edge is caught
+        // promptly rather than waiting up to a second for the next sweep
+        c.process(PROPERTIES_CHANGED_DEBOUNCE)?;
+        sweep_pending_properties_changed(
+            &c,
+            shared,
+            &shadow,
+            &pending,
+            collect_substate,
+            record_public,
+        );
+        sweep_topology_dirty(config, shared, &shadow, &topology_dirty);
+    }
+}
+
+/// Window used to coalesce a burst of UnitNew/UnitRemoved signals (e.g. many units appearing
+/// during boot or a `systemctl daemon-reload`) into a single reseed rather than one per
+/// signal - a reseed walks every unit over dbus, which is far more expensive than applying
+/// one unit's transition, so it shouldn't run more often than topology actually settles.
+const TOPOLOGY_RESEED_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Run once per main-loop tick: if a UnitNew/UnitRemoved signal has arrived and
+/// [`TOPOLOGY_RESEED_DEBOUNCE`] has passed since the last one, reseed `shared` and `shadow`
+/// from a fresh `ListUnits` pass. Skips the sysinfo sampling and dependency walk
+/// `collect_unit_stats` would otherwise do (see its `sample_process_resources` parameter)
+/// since those are exactly the per-unit dbus round trips and blocking sysinfo sleep this
+/// debounce exists to keep off the dispatch path; `service_stats`/`dependency_stats` are
+/// carried over from the previous snapshot unchanged rather than going blank until the next
+/// full reseed. Leaves `topology_dirty` set on a failed reseed so the next tick retries,
+/// rather than silently dropping the pending reseed.
+///
+/// Only called from `run_event_driven_pass`'s own single-threaded main loop, after
+/// `c.process()` has returned for this tick - `collect_unit_stats` below uses its own
+/// independent connection and never drives `c`'s reactor, so no further UnitNew/UnitRemoved
+/// on `c` can be dispatched while a reseed is in flight and there's no concurrent writer to
+/// race against.
+fn sweep_topology_dirty(
+    config: &Arc<crate::config::Config>,
+    shared: &Arc<Mutex<SystemdUnitStats>>,
+    shadow: &Arc<Mutex<HashMap<String, UnitStates>>>,
+    topology_dirty: &Arc<Mutex<Option<Instant>>>,
+) {
+    {
+        let dirty = topology_dirty.lock().unwrap();
+        match *dirty {
+            Some(since) if since.elapsed() >= TOPOLOGY_RESEED_DEBOUNCE => {}
+            _ => return,
+        }
+    }
+    match collect_unit_stats(config, false) {
+        Ok((mut stats, units)) => {
+            let mut shared = shared.lock().unwrap();
+            stats.service_stats = shared.service_stats.clone();
+            stats.dependency_stats = shared.dependency_stats.clone();
+            *shared = stats;
+            *shadow.lock().unwrap() = build_unit_state_shadow(&units);
+            *topology_dirty.lock().unwrap() = None;
+        }
+        Err(err) => error!("Failed to reseed after unit topology change: {:?}", err),
+    }
+}
+
+/// Build the internal "last known state" map [`apply_unit_state`] decrements against,
+/// independent of `config.units.state_stats`/`substate_stats` - see the comment on `shadow`
+/// in [`run_event_driven_pass`] for why that independence matters.
+fn build_unit_state_shadow(units: &[RawUnit]) -> HashMap<String, UnitStates> {
+    units
+        .iter()
+        .map(|unit| {
+            let active_state = SystemdUnitActiveState::from_str(&unit.3)
+                .unwrap_or(SystemdUnitActiveState::unknown);
+            let load_state = SystemdUnitLoadState::from_str(&unit.2.replace('-', "_"))
+                .unwrap_or(SystemdUnitLoadState::unknown);
+            (
+                unit.0.clone(),
+                UnitStates {
+                    active_state,
+                    load_state,
+                    sub_state: unit.4.clone(),
+                    unhealthy: is_unit_unhealthy(active_state, load_state),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Re-read the Manager's current outstanding job count and replace `jobs_queued` with it.
+/// Called on every JobNew/JobRemoved rather than incrementing/decrementing independently,
+/// so it can never drift from, or be silently undone by, a reseed from `parse_unit_state`.
+fn refresh_jobs_queued(c: &Connection, shared: &Arc<Mutex<SystemdUnitStats>>) {
+    use crate::dbus::systemd::OrgFreedesktopSystemd1Manager;
+
+    let manager = c.with_proxy(
+        "org.freedesktop.systemd1",
+        "/org/freedesktop/systemd1",
+        Duration::new(5, 0),
+    );
+    match manager.list_jobs() {
+        Ok(jobs) => shared.lock().unwrap().jobs_queued = jobs.len() as u64,
+        Err(err) => error!("Failed to refresh jobs_queued: {:?}", err),
+    }
+}
+
+/// Adjust the per-load-state counter by +1 (`increment = true`) or -1. `unknown`/`error`
+/// aren't tracked as standalone counters so are a no-op, matching [`parse_unit`].
+fn adjust_load_count(
+    stats: &mut SystemdUnitStats,
+    load_state: SystemdUnitLoadState,
+    increment: bool,
+) {
+    let counter = match load_state {
+        SystemdUnitLoadState::loaded => &mut stats.loaded_units,
+        SystemdUnitLoadState::masked => &mut stats.masked_units,
+        SystemdUnitLoadState::not_found => &mut stats.not_found_units,
+        SystemdUnitLoadState::stub => &mut stats.stub_units,
+        SystemdUnitLoadState::merged => &mut stats.merged_units,
+        SystemdUnitLoadState::bad_setting => &mut stats.bad_setting_units,
+        SystemdUnitLoadState::unknown | SystemdUnitLoadState::error => return,
+    };
+    *counter = if increment {
+        *counter + 1
+    } else {
+        counter.saturating_sub(1)
+    };
+}
+
+/// Adjust the per-active-state counter, mirroring [`adjust_load_count`] for ActiveState.
+fn adjust_active_count(
+    stats: &mut SystemdUnitStats,
+    active_state: SystemdUnitActiveState,
+    increment: bool,
+) {
+    let counter = match active_state {
+        SystemdUnitActiveState::active => &mut stats.active_units,
+        SystemdUnitActiveState::inactive => &mut stats.inactive_units,
+        SystemdUnitActiveState::failed => &mut stats.failed_units,
+        _ => return,
+    };
+    *counter = if increment {
+        *counter + 1
+    } else {
+        counter.saturating_sub(1)
+    };
+}
+
+/// Entry point for a unit's `PropertiesChanged` signal: applies immediately on the leading
+/// edge of a burst, then coalesces any further signals within [`PROPERTIES_CHANGED_DEBOUNCE`]
+/// into a single `pending` entry for [`sweep_pending_properties_changed`] to apply once the
+/// burst goes quiet - so the unit's state as of its *last* transition in a fast burst is
+/// always eventually reflected, rather than being dropped if no further signal arrives.
+fn apply_properties_changed(
+    c: &Connection,
+    shared: &Arc<Mutex<SystemdUnitStats>>,
+    shadow: &Arc<Mutex<HashMap<String, UnitStates>>>,
+    pending: &Arc<Mutex<HashMap<String, PendingPropertiesChanged>>>,
+    allow_matcher: &UnitMatcher,
+    block_matcher: &UnitMatcher,
+    collect_substate: bool,
+    record_public: bool,
+    msg: &dbus::Message,
+) {
+    use crate::dbus::units::OrgFreedesktopSystemd1Unit;
+
+    let unit_path = match msg.path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let proxy = c.with_proxy(
+        "org.freedesktop.systemd1",
+        unit_path.clone(),
+        Duration::new(2, 0),
+    );
+    let unit_name = match proxy.id() {
+        Ok(id) => id,
+        Err(err) => {
+            debug!("Ignoring PropertiesChanged on {}: {:?}", unit_path, err);
+            return;
+        }
+    };
+
+    if block_matcher.is_match(&unit_name) {
+        return;
+    }
+    if !allow_matcher.is_empty() && !allow_matcher.is_match(&unit_name) {
+        return;
+    }
+
+    let now = Instant::now();
+    {
+        let mut pending = pending.lock().unwrap();
+        if let Some(existing) = pending.get_mut(&unit_name) {
+            // Already mid-burst: coalesce into the pending entry rather than re-applying
+            // now - the sweep will pick this unit up once `last_seen` goes quiet
+            existing.last_seen = now;
+            debug!("Debouncing PropertiesChanged for {}", unit_name);
+            return;
+        }
+        // `unit_path` as given borrows from `msg`; re-box into an owned Path so the entry
+        // can outlive this signal callback until the sweep applies it
+        let owned_path = dbus::Path::new(unit_path.to_string()).unwrap();
+        pending.insert(
+            unit_name.clone(),
+            PendingPropertiesChanged {
+                path: owned_path,
+                last_seen: now,
+            },
+        );
+    }
+
+    apply_unit_state(
+        c,
+        shared,
+        shadow,
+        collect_substate,
+        record_public,
+        &unit_name,
+        unit_path,
+    );
+}
+
+/// Run once per main-loop tick: applies any unit whose `PropertiesChanged` burst has been
+/// quiet for at least [`PROPERTIES_CHANGED_DEBOUNCE`], so a burst's last transition is never
+/// silently dropped just because no further signal arrived to re-trigger it.
+fn sweep_pending_properties_changed(
+    c: &Connection,
+    shared: &Arc<Mutex<SystemdUnitStats>>,
+    shadow: &Arc<Mutex<HashMap<String, UnitStates>>>,
+    pending: &Arc<Mutex<HashMap<String, PendingPropertiesChanged>>>,
+    collect_substate: bool,
+    record_public: bool,
+) {
+    let now = Instant::now();
+    let ready: Vec<(String, dbus::Path<'static>)> = {
+        let mut pending = pending.lock().unwrap();
+        let ready_names: Vec<String> = pending
+            .iter()
+            .filter(|(_, entry)| now.duration_since(entry.last_seen) >= PROPERTIES_CHANGED_DEBOUNCE)
+            .map(|(name, _)| name.clone())
+            .collect();
+        ready_names
+            .into_iter()
+            .filter_map(|name| pending.remove(&name).map(|entry| (name, entry.path)))
+            .collect()
+    };
+    for (unit_name, unit_path) in &ready {
+        apply_unit_state(
+            c,
+            shared,
+            shadow,
+            collect_substate,
+            record_public,
+            unit_name,
+            unit_path,
+        );
+    }
+}
+
+/// Re-read a unit's current ActiveState/LoadState/SubState from dbus and apply it to
+/// `shared` in place: decrement its old active/load/sub-state buckets and increment the new
+/// ones, so the aggregate counters never have to be fully recomputed just because one unit
+/// changed state. The old state comes from `shadow` rather than `stats.unit_states` - the
+/// latter is only populated when `record_public` (`state_stats`/`substate_stats`) is set, and
+/// using it as the decrement source would double-count every transition past the first when
+/// both flags are off, since there'd never be an old entry to find. `collect_substate` mirrors
+/// `config.units.substate_stats` so the event-driven path records the same fields the polling
+/// path (`parse_state`) would for the same config.
+fn apply_unit_state(
+    c: &Connection,
+    shared: &Arc<Mutex<SystemdUnitStats>>,
+    shadow: &Arc<Mutex<HashMap<String, UnitStates>>>,
+    collect_substate: bool,
+    record_public: bool,
+    unit_name: &str,
+    unit_path: &dbus::Path,
+) {
+    use crate::dbus::units::OrgFreedesktopSystemd1Unit;
+
+    let proxy = c.with_proxy(
+        "org.freedesktop.systemd1",
+        unit_path.clone(),
+        Duration::new(2, 0),
+    );
+    let new_active = SystemdUnitActiveState::from_str(&proxy.active_state().unwrap_or_default())
+        .unwrap_or(SystemdUnitActiveState::unknown);
+    let new_load =
+        SystemdUnitLoadState::from_str(&proxy.load_state().unwrap_or_default().replace('-', "_"))
+            .unwrap_or(SystemdUnitLoadState::unknown);
+    let new_sub_state = if collect_substate {
+        proxy.sub_state().unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let unit_type = unit_name.split('.').nth(1).unwrap_or("").to_string();
+
+    let mut stats = shared.lock().unwrap();
+    let mut shadow = shadow.lock().unwrap();
+
+    // Decrement whatever this unit was counted as before, so the counters stay internally
+    // consistent (active + inactive + failed + ... tracks each transition atomically under
+    // the same stats lock) rather than only ever growing
+    if let Some(old_state) = shadow.get(unit_name).cloned() {
+        adjust_load_count(&mut stats, old_state.load_state, false);
+        adjust_active_count(&mut stats, old_state.active_state, false);
+        if collect_substate && !old_state.sub_state.is_empty() {
+            let key = format!("{}.{}", unit_type, old_state.sub_state);
+            if let Some(count) = stats.sub_state_stats.get_mut(&key) {
+                *count = count.saturating_sub(1);
+            }
+        }
+    }
+
+    adjust_load_count(&mut stats, new_load, true);
+    adjust_active_count(&mut stats, new_active, true);
+    if collect_substate {
+        *stats
+            .sub_state_stats
+            .entry(format!("{}.{}", unit_type, new_sub_state))
+            .or_insert(0) += 1;
+    }
+
+    let new_state = UnitStates {
+        active_state: new_active,
+        load_state: new_load,
+        sub_state: new_sub_state,
+        unhealthy: is_unit_unhealthy(new_active, new_load),
+    };
+    if record_public {
+        stats
+            .unit_states
+            .insert(unit_name.to_string(), new_state.clone());
+    }
+    shadow.insert(unit_name.to_string(), new_state);
 }
 
 #[cfg(test)]
@@ -414,6 +1459,20 @@ mod tests {
             SystemdUnitActiveState::active,
             SystemdUnitLoadState::error,
         ));
+        // stub/merged are transient/benign, not actionable
+        assert!(!is_unit_unhealthy(
+            SystemdUnitActiveState::inactive,
+            SystemdUnitLoadState::stub
+        ));
+        assert!(!is_unit_unhealthy(
+            SystemdUnitActiveState::inactive,
+            SystemdUnitLoadState::merged
+        ));
+        // bad-setting is always unhealthy
+        assert!(is_unit_unhealthy(
+            SystemdUnitActiveState::active,
+            SystemdUnitLoadState::bad_setting
+        ));
     }
 
     #[test]
@@ -422,12 +1481,14 @@ mod tests {
         let expected_stats = SystemdUnitStats {
             active_units: 0,
             automount_units: 0,
+            bad_setting_units: 0,
             device_units: 0,
             failed_units: 0,
             inactive_units: 0,
             jobs_queued: 0,
             loaded_units: 0,
             masked_units: 0,
+            merged_units: 0,
             mount_units: 0,
             not_found_units: 0,
             path_units: 0,
@@ -435,6 +1496,7 @@ mod tests {
             service_units: 0,
             slice_units: 0,
             socket_units: 0,
+            stub_units: 0,
             target_units: 0,
             timer_units: 0,
             total_units: 0,
@@ -444,20 +1506,30 @@ mod tests {
                 UnitStates {
                     active_state: SystemdUnitActiveState::inactive,
                     load_state: SystemdUnitLoadState::loaded,
+                    sub_state: String::new(),
                     unhealthy: true,
                 },
             )]),
+            sub_state_stats: HashMap::new(),
+            dependency_stats: DependencyStats::default(),
         };
         let mut stats = SystemdUnitStats::default();
         let systemd_unit = get_unit_file();
+        let empty_matcher = UnitMatcher::compile(&[], UnitMatchMode::Literal);
 
         // Test no allow list or blocklist
-        parse_state(&mut stats, systemd_unit.clone(), &vec![], &vec![]);
+        parse_state(
+            &mut stats,
+            systemd_unit.clone(),
+            &empty_matcher,
+            &empty_matcher,
+            false,
+        );
         assert_eq!(expected_stats, stats);
 
         // Create some allow/block lists
-        let allowlist = Vec::from([test_unit_name.clone()]);
-        let blocklist = Vec::from([test_unit_name]);
+        let allowlist = UnitMatcher::compile(&[test_unit_name.clone()], UnitMatchMode::Literal);
+        let blocklist = UnitMatcher::compile(&[test_unit_name], UnitMatchMode::Literal);
 
         // test no blocklist and only allow list - Should equal the same as no lists above
         let mut allowlist_stats = SystemdUnitStats::default();
@@ -465,28 +1537,59 @@ mod tests {
             &mut allowlist_stats,
             systemd_unit.clone(),
             &allowlist,
-            &vec![],
+            &empty_matcher,
+            false,
         );
         assert_eq!(expected_stats, allowlist_stats);
 
         // test blocklist with allow list (show it's preferred)
         let mut blocklist_stats = SystemdUnitStats::default();
         let expected_blocklist_stats = SystemdUnitStats::default();
-        parse_state(&mut blocklist_stats, systemd_unit, &allowlist, &blocklist);
+        parse_state(
+            &mut blocklist_stats,
+            systemd_unit.clone(),
+            &allowlist,
+            &blocklist,
+            false,
+        );
         assert_eq!(expected_blocklist_stats, blocklist_stats);
     }
 
+    #[test]
+    fn test_state_parse_substate() {
+        let test_unit_name = String::from("apport-autoreport.timer");
+        let systemd_unit = get_unit_file();
+        let empty_matcher = UnitMatcher::compile(&[], UnitMatchMode::Literal);
+
+        let mut stats = SystemdUnitStats::default();
+        parse_state(
+            &mut stats,
+            systemd_unit,
+            &empty_matcher,
+            &empty_matcher,
+            true,
+        );
+
+        assert_eq!(
+            stats.unit_states.get(&test_unit_name).unwrap().sub_state,
+            "dead"
+        );
+        assert_eq!(stats.sub_state_stats.get("timer.dead").copied(), Some(1));
+    }
+
     #[test]
     fn test_unit_parse() {
         let expected_stats = SystemdUnitStats {
             active_units: 0,
             automount_units: 0,
+            bad_setting_units: 0,
             device_units: 0,
             failed_units: 0,
             inactive_units: 1,
             jobs_queued: 0,
             loaded_units: 1,
             masked_units: 0,
+            merged_units: 0,
             mount_units: 0,
             not_found_units: 0,
             path_units: 0,
@@ -494,11 +1597,14 @@ mod tests {
             service_units: 0,
             slice_units: 0,
             socket_units: 0,
+            stub_units: 0,
             target_units: 0,
             timer_units: 1,
             total_units: 0,
             service_stats: HashMap::new(),
             unit_states: HashMap::new(),
+            sub_state_stats: HashMap::new(),
+            dependency_stats: DependencyStats::default(),
         };
         let mut stats = SystemdUnitStats::default();
         let systemd_unit = get_unit_file();
@@ -511,4 +1617,106 @@ mod tests {
         assert!(SystemdUnitActiveState::iter().collect::<Vec<_>>().len() > 0);
         assert!(SystemdUnitLoadState::iter().collect::<Vec<_>>().len() > 0);
     }
+
+    #[test]
+    fn test_unit_matcher() {
+        let literal = UnitMatcher::compile(
+            &[String::from("apport-autoreport.timer")],
+            UnitMatchMode::Literal,
+        );
+        assert!(literal.is_match("apport-autoreport.timer"));
+        assert!(!literal.is_match("apport-autoreport.service"));
+
+        let glob = UnitMatcher::compile(&[String::from("user@*.service")], UnitMatchMode::Glob);
+        assert!(glob.is_match("user@1000.service"));
+        assert!(!glob.is_match("user@1000.slice"));
+
+        let regex = UnitMatcher::compile(
+            &[String::from("^systemd-.*\\.socket$")],
+            UnitMatchMode::Regex,
+        );
+        assert!(regex.is_match("systemd-journald.socket"));
+        assert!(!regex.is_match("apport-autoreport.timer"));
+
+        let empty = UnitMatcher::compile(&[], UnitMatchMode::Literal);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_unit_matcher_invalid_fails_closed() {
+        // A broken allowlist must never be treated as "empty" (which means match
+        // everything) - it has to match nothing instead
+        let invalid_regex =
+            UnitMatcher::compile(&[String::from("(unterminated")], UnitMatchMode::Regex);
+        assert!(!invalid_regex.is_empty());
+        assert!(!invalid_regex.is_match("apport-autoreport.timer"));
+
+        let invalid_glob = UnitMatcher::compile(&[String::from("[")], UnitMatchMode::Glob);
+        assert!(!invalid_glob.is_empty());
+        assert!(!invalid_glob.is_match("apport-autoreport.timer"));
+    }
+
+    #[test]
+    fn test_unit_matcher_regex_partial_validity_matches_glob_policy() {
+        // One bad regex among otherwise-valid ones shouldn't disable the whole set - same
+        // fail-closed-only-when-nothing-compiles policy the Glob arm already uses
+        let mixed = UnitMatcher::compile(
+            &[
+                String::from("^systemd-.*\\.socket$"),
+                String::from("(unterminated"),
+            ],
+            UnitMatchMode::Regex,
+        );
+        assert!(!mixed.is_empty());
+        assert!(mixed.is_match("systemd-journald.socket"));
+    }
+
+    #[test]
+    fn test_find_ordering_cycles_none() {
+        let ordering_edges = HashMap::from([
+            (String::from("a.service"), vec![String::from("b.service")]),
+            (String::from("b.service"), vec![String::from("c.service")]),
+        ]);
+        assert!(find_ordering_cycles(&ordering_edges).is_empty());
+    }
+
+    #[test]
+    fn test_find_ordering_cycles_detects_back_edge() {
+        let ordering_edges = HashMap::from([
+            (String::from("a.service"), vec![String::from("b.service")]),
+            (String::from("b.service"), vec![String::from("a.service")]),
+        ]);
+        let cycles = find_ordering_cycles(&ordering_edges);
+        assert_eq!(cycles.len(), 1);
+        assert!(cycles[0].contains(&String::from("a.service")));
+        assert!(cycles[0].contains(&String::from("b.service")));
+    }
+
+    #[test]
+    fn test_top_processes_by_cpu() {
+        let samples = vec![
+            TopProcess {
+                pid: 1,
+                command: String::from("low-cpu"),
+                cpu_percent_milli: 100,
+                resident_memory_bytes: 4096,
+            },
+            TopProcess {
+                pid: 2,
+                command: String::from("high-cpu"),
+                cpu_percent_milli: 5000,
+                resident_memory_bytes: 1024,
+            },
+            TopProcess {
+                pid: 3,
+                command: String::from("mid-cpu"),
+                cpu_percent_milli: 2000,
+                resident_memory_bytes: 2048,
+            },
+        ];
+        let top = top_processes_by_cpu(samples, 2);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].pid, 2);
+        assert_eq!(top[1].pid, 3);
+    }
 }